@@ -1,14 +1,133 @@
 
-use std::fs::File;
-use std::io::{self, BufRead};
 use std::cmp::Ordering;
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
 
 
+#[derive(Debug)]
 enum Item {
     Integer(usize),
     List(Box<Vec<Item>>),
 }
 
+#[derive(Debug)]
+enum ParseErrorReason {
+    UnexpectedChar(u8),
+    UnexpectedEof,
+    TrailingData,
+    Io(String),
+    InvalidUtf8,
+    OutOfRange,
+}
+
+#[derive(Debug)]
+struct ParseError {
+    offset: usize,
+    reason: ParseErrorReason,
+}
+
+impl ParseError {
+    fn new(offset: usize, reason: ParseErrorReason) -> Self {
+        return ParseError { offset, reason };
+    }
+
+    fn offset_by(self, delta: usize) -> Self {
+        return ParseError { offset: self.offset + delta, reason: self.reason };
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.reason {
+            ParseErrorReason::UnexpectedChar(ch) => {
+                return write!(f, "unexpected char {:?} at byte {}", ch as char, self.offset);
+            },
+            ParseErrorReason::UnexpectedEof => {
+                return write!(f, "unexpected end of input at byte {}", self.offset);
+            },
+            ParseErrorReason::TrailingData => {
+                return write!(f, "trailing data at byte {}", self.offset);
+            },
+            ParseErrorReason::Io(ref message) => {
+                return write!(f, "I/O error: {}", message);
+            },
+            ParseErrorReason::InvalidUtf8 => {
+                return write!(f, "invalid UTF-8 at byte {}", self.offset);
+            },
+            ParseErrorReason::OutOfRange => {
+                return write!(f, "integer out of range at byte {}", self.offset);
+            },
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Item {
+    fn parse(s: &str) -> Result<(Item, &str), ParseError> {
+        match s.as_bytes().first() {
+            Some(b'[') => {
+                let mut rest = &s[1..];
+                let mut list: Vec<Item> = Vec::new();
+                let mut after_item = false;
+                loop {
+                    match rest.as_bytes().first() {
+                        Some(b']') => {
+                            if !list.is_empty() && !after_item {
+                                return Err(ParseError::new(s.len() - rest.len(), ParseErrorReason::UnexpectedChar(b']')));
+                            }
+                            rest = &rest[1..];
+                            break;
+                        },
+                        Some(b',') => {
+                            if !after_item {
+                                return Err(ParseError::new(s.len() - rest.len(), ParseErrorReason::UnexpectedChar(b',')));
+                            }
+                            rest = &rest[1..];
+                            after_item = false;
+                        },
+                        Some(&ch) => {
+                            if after_item {
+                                return Err(ParseError::new(s.len() - rest.len(), ParseErrorReason::UnexpectedChar(ch)));
+                            }
+                            let consumed = s.len() - rest.len();
+                            let (item, tail) = Item::parse(rest).map_err(|e| e.offset_by(consumed))?;
+                            list.push(item);
+                            rest = tail;
+                            after_item = true;
+                        },
+                        None => {
+                            return Err(ParseError::new(s.len(), ParseErrorReason::UnexpectedEof));
+                        },
+                    }
+                }
+                return Ok((Item::List(Box::new(list)), rest));
+            },
+            Some(b'0'..=b'9') => {
+                let bytes = s.as_bytes();
+                let mut end = 0;
+                while matches!(bytes.get(end), Some(b'0'..=b'9')) {
+                    end += 1;
+                }
+                let value: usize = match s[..end].parse() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        return Err(ParseError::new(0, ParseErrorReason::OutOfRange));
+                    },
+                };
+                return Ok((Item::Integer(value), &s[end..]));
+            },
+            Some(&ch) => {
+                return Err(ParseError::new(0, ParseErrorReason::UnexpectedChar(ch)));
+            },
+            None => {
+                return Err(ParseError::new(0, ParseErrorReason::UnexpectedEof));
+            },
+        }
+    }
+}
+
 impl Eq for Item {}
 
 impl PartialEq for Item {
@@ -23,6 +142,21 @@ impl PartialOrd for Item {
     }
 }
 
+fn cmp_int_list(x: usize, list: &[Item]) -> Ordering {
+    match list.first() {
+        None => {
+            return Ordering::Greater;
+        },
+        Some(first) => {
+            let c = Item::Integer(x).cmp(first);
+            if c == Ordering::Equal {
+                return 1.cmp(&list.len());
+            }
+            return c;
+        },
+    }
+}
+
 impl Ord for Item {
     fn cmp(&self, other: &Self) -> Ordering {
         match self {
@@ -30,15 +164,13 @@ impl Ord for Item {
                 Item::Integer(y) => {
                     return x.cmp(y);
                 },
-                Item::List(_) => {
-                    let xl = Item::List(Box::new(vec![Item::Integer(*x)]));
-                    return xl.cmp(other);
+                Item::List(y) => {
+                    return cmp_int_list(*x, y);
                 },
             },
             Item::List(x) => match other {
                 Item::Integer(y) => {
-                    let yl = Item::List(Box::new(vec![Item::Integer(*y)]));
-                    return self.cmp(&yl);
+                    return cmp_int_list(*y, x).reverse();
                 },
                 Item::List(y) => {
                     for i in 0 .. usize::min(x.len(), y.len()) {
@@ -56,6 +188,7 @@ impl Ord for Item {
     }
 }
 
+#[derive(Debug)]
 struct Pair {
     left: Item,
     right: Item,
@@ -63,130 +196,214 @@ struct Pair {
 
 type Problem = Vec<Pair>;
 
-fn load(filename: &str) -> Problem {
-    let file = File::open(filename).unwrap();
-    let mut problem: Problem = Vec::new();
-    let mut stack: Vec<Item> = Vec::new();
-    let mut line_number: usize = 0;
-
-    for line in io::BufReader::new(file).lines() {
-        line_number += 1;
-        if let Ok(line_string) = line {
-            let mut integer_bytes: usize = 0;
-            let mut integer_value: usize = 0;
-            assert!(stack.is_empty());
-            stack.push(Item::List(Box::new(Vec::new())));
-            for ch in line_string.bytes() {
-                match ch {
-                    b']' | b',' | b'\n' => {
-                        if integer_bytes != 0 {
-                            match stack.last_mut().unwrap() {
-                                Item::Integer(_) => {
-                                    panic!();
-                                },
-                                Item::List(x) => {
-                                    x.push(Item::Integer(integer_value));
-                                },
-                            }
-                        }
-                        integer_bytes = 0;
-                        integer_value = 0;
-                    },
-                    b'[' => {
-                        assert!(integer_bytes == 0);
-                    },
-                    _ => {
-                        let digit = (ch - b'0') as usize;
-                        assert!(digit < 10);
-                        integer_bytes += 1;
-                        integer_value *= 10;
-                        integer_value += digit;
-                    },
-                }
-                match ch {
-                    b'[' => {
-                        stack.push(Item::List(Box::new(Vec::new())));
-                    },
-                    b']' => {
-                        let child = stack.pop().unwrap();
-                        match stack.last_mut().unwrap() {
-                            Item::Integer(_) => {
-                                panic!();
-                            },
-                            Item::List(x) => {
-                                x.push(child);
-                            },
-                        }
-                    },
-                    _ => {},
-                }
-            }
-            assert_eq!(stack.len(), 1);
-            match line_number % 3 {
-                1 => {
-                    problem.push(Pair {
-                        left: stack.pop().unwrap(),
-                        right: Item::List(Box::new(Vec::new())),
-                    });
-                },
-                2 => {
-                    problem.last_mut().unwrap().right = stack.pop().unwrap();
-                },
-                _ => {
-                    stack.pop();
-                },
-            }
-            assert!(stack.is_empty());
-        }
+fn parse_line(line_string: &str) -> Result<Item, ParseError> {
+    let (item, tail) = Item::parse(line_string)?;
+    if !tail.is_empty() {
+        return Err(ParseError::new(line_string.len() - tail.len(), ParseErrorReason::TrailingData));
     }
-    return problem;
+    return Ok(item);
 }
 
-fn print_list(item: &Item) {
-    match item {
-        Item::Integer(_) => {
-            panic!();
-        },
-        Item::List(x) => {
-            let mut first = true;
-            for y in x.iter() {
-                if !first {
-                    print!(",");
-                }
-                print_item(y);
-                first = false;
-            }
-        },
+fn for_each_record(text: &str, mut emit: impl FnMut(Pair)) -> Result<(), ParseError> {
+    let mut pending_left: Option<Item> = None;
+    for (i, line) in text.lines().enumerate() {
+        match (i + 1) % 3 {
+            1 => {
+                pending_left = Some(parse_line(line)?);
+            },
+            2 => {
+                let left = pending_left.take().unwrap();
+                emit(Pair { left, right: parse_line(line)? });
+            },
+            _ => {},
+        }
     }
+    return Ok(());
 }
 
-fn print_item(item: &Item) {
-    match item {
-        Item::Integer(x) => {
-            print!("{}", x);
-        },
-        Item::List(_) => {
-            print!("[");
-            print_list(&item);
-            print!("]");
+fn load(filename: &str) -> Result<Problem, ParseError> {
+    let bytes = fs::read(filename).map_err(|e| ParseError::new(0, ParseErrorReason::Io(e.to_string())))?;
+    let text = std::str::from_utf8(&bytes).map_err(|e| ParseError::new(e.valid_up_to(), ParseErrorReason::InvalidUtf8))?;
+    let mut problem: Problem = Vec::new();
+    for_each_record(text, |pair| problem.push(pair))?;
+    return Ok(problem);
+}
+
+fn load_streaming(filename: &str) -> mpsc::Receiver<Result<Pair, ParseError>> {
+    let (tx, rx) = mpsc::channel();
+    let filename = filename.to_string();
+    thread::spawn(move || {
+        let bytes = match fs::read(&filename) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = tx.send(Err(ParseError::new(0, ParseErrorReason::Io(e.to_string()))));
+                return;
+            },
+        };
+        let text = match std::str::from_utf8(&bytes) {
+            Ok(text) => text,
+            Err(e) => {
+                let _ = tx.send(Err(ParseError::new(e.valid_up_to(), ParseErrorReason::InvalidUtf8)));
+                return;
+            },
+        };
+        if let Err(e) = for_each_record(text, |pair| {
+            let _ = tx.send(Ok(pair));
+        }) {
+            let _ = tx.send(Err(e));
+        }
+    });
+    return rx;
+}
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Item::Integer(x) => {
+                return write!(f, "{}", x);
+            },
+            Item::List(x) => {
+                write!(f, "[")?;
+                let mut first = true;
+                for y in x.iter() {
+                    if !first {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", y)?;
+                    first = false;
+                }
+                return write!(f, "]");
+            },
         }
     }
 }
 
 fn print_problem(problem: &Problem) {
     for pair in problem {
-        print_list(&pair.left);
-        println!();
-        print_list(&pair.right);
-        println!();
+        println!("{}", pair.left);
+        println!("{}", pair.right);
         println!();
     }
 }
 
 
+fn sum_ordered_pair_indices(problem: &Problem) -> usize {
+    let mut sum: usize = 0;
+    for (i, pair) in problem.iter().enumerate() {
+        if pair.left.cmp(&pair.right) == Ordering::Less {
+            sum += i + 1;
+        }
+    }
+    return sum;
+}
+
+fn make_divider(value: usize) -> Item {
+    return Item::List(Box::new(vec![Item::List(Box::new(vec![Item::Integer(value)]))]));
+}
+
+fn decoder_key(problem: Problem) -> usize {
+    let mut items: Vec<Item> = Vec::new();
+    for pair in problem {
+        items.push(pair.left);
+        items.push(pair.right);
+    }
+    items.push(make_divider(2));
+    items.push(make_divider(6));
+    items.sort();
+
+    let first = items.iter().position(|item| *item == make_divider(2)).unwrap() + 1;
+    let second = items.iter().position(|item| *item == make_divider(6)).unwrap() + 1;
+    return first * second;
+}
+
 fn main() {
-    let p = load("input");
+    let p = match load("input") {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("failed to parse input: {}", e);
+            std::process::exit(1);
+        },
+    };
     print_problem(&p);
+    let index_sum = sum_ordered_pair_indices(&p);
+    println!("sum of ordered pair indices: {}", index_sum);
+    let key = decoder_key(p);
+    println!("decoder key: {}", key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_and_parse_round_trip() {
+        let problem = load("sample_input").unwrap();
+        for pair in &problem {
+            for item in [&pair.left, &pair.right] {
+                let text = item.to_string();
+                let (parsed, tail) = Item::parse(&text).unwrap();
+                assert!(tail.is_empty());
+                assert_eq!(*item, parsed);
+            }
+        }
+    }
+
+    #[test]
+    fn display_and_parse_round_trip_bare_integer() {
+        let item = Item::Integer(42);
+        let text = item.to_string();
+        let (parsed, tail) = Item::parse(&text).unwrap();
+        assert!(tail.is_empty());
+        assert_eq!(item, parsed);
+    }
+
+    #[test]
+    fn sample_index_sum_and_decoder_key() {
+        let problem = load("sample_input").unwrap();
+        assert_eq!(sum_ordered_pair_indices(&problem), 17);
+        assert_eq!(decoder_key(problem), 140);
+    }
+
+    #[test]
+    fn cmp_int_list_against_empty_single_and_longer_lists() {
+        assert_eq!(cmp_int_list(4, &[]), Ordering::Greater);
+        assert_eq!(cmp_int_list(4, &[Item::Integer(4)]), Ordering::Equal);
+        assert_eq!(cmp_int_list(4, &[Item::Integer(4), Item::Integer(0)]), Ordering::Less);
+        assert_eq!(cmp_int_list(4, &[Item::Integer(5)]), Ordering::Less);
+        assert_eq!(cmp_int_list(4, &[Item::Integer(3)]), Ordering::Greater);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_input() {
+        assert!(Item::parse("[1,,2]").is_err());
+        assert!(Item::parse("[1,]").is_err());
+        assert!(Item::parse("[1,2").is_err());
+        assert!(parse_line("[1,2]]").is_err());
+        assert!(parse_line("[1,2] ").is_err());
+        assert!(Item::parse("[99999999999999999999999999999999]").is_err());
+    }
+
+    #[test]
+    fn load_streaming_matches_load() {
+        let expected = load("sample_input").unwrap();
+        let rx = load_streaming("sample_input");
+        let streamed: Vec<Pair> = rx.iter().map(|result| result.unwrap()).collect();
+
+        assert_eq!(streamed.len(), expected.len());
+        for (streamed_pair, expected_pair) in streamed.iter().zip(expected.iter()) {
+            assert_eq!(streamed_pair.left, expected_pair.left);
+            assert_eq!(streamed_pair.right, expected_pair.right);
+        }
+    }
+
+    #[test]
+    fn load_streaming_reports_missing_file() {
+        let rx = load_streaming("no_such_file_for_testing");
+        match rx.recv() {
+            Ok(Err(_)) => {},
+            other => panic!("expected a ParseError on the channel, got {:?}", other),
+        }
+    }
 }
 
 